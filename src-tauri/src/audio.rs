@@ -1,23 +1,139 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use rustfft::{FftPlanner, num_complex::Complex};
+use std::collections::VecDeque;
 use tauri::AppHandle;
 
-pub fn start(app: AppHandle) {
+use crate::config::AudioConfig;
+
+pub fn start(app: AppHandle, audio_cfg: AudioConfig) {
     tauri::async_runtime::spawn(async move {
-        if let Err(e) = run(app.clone()) {
+        if let Err(e) = run(app.clone(), audio_cfg) {
             eprintln!("audio error: {e:?}");
         }
     });
 }
 
-fn run(app: AppHandle) -> anyhow::Result<()> {
+/// Tracks spectral flux onset envelope and autocorrelates it to follow tempo,
+/// so layer fades and visuals can lock to the beat of whatever is playing.
+///
+/// Hop timing is derived each call from the number of samples actually handed
+/// to `process` by the audio callback, rather than assumed to match the FFT
+/// size, since cpal may deliver a different number of samples per callback.
+struct BeatDetector {
+    sensitivity: f32,
+    bpm_min: f32,
+    bpm_max: f32,
+    sample_rate: f32,
+    hop_rate: f32,
+    last_mean: f32,
+    prev_mag: Vec<f32>,
+    envelope: VecDeque<f32>,
+    window_len: usize,
+    hops_since_onset: usize,
+    refractory_hops: usize,
+}
+
+impl BeatDetector {
+    fn new(fft_size: usize, sample_rate: f32, cfg: AudioConfig) -> Self {
+        Self {
+            sensitivity: cfg.sensitivity,
+            bpm_min: cfg.bpm_min,
+            bpm_max: cfg.bpm_max,
+            sample_rate,
+            hop_rate: 0.0,
+            last_mean: 0.0,
+            prev_mag: vec![0.0; fft_size],
+            envelope: VecDeque::new(),
+            window_len: 8,
+            hops_since_onset: 0,
+            refractory_hops: 1,
+        }
+    }
+
+    /// Feeds one hop's magnitude spectrum into the detector. `hop_samples` is the
+    /// number of audio samples this hop actually covers, used to convert the
+    /// window, refractory gap and autocorrelation lags from real time to hops.
+    /// Returns whether this hop is an onset, and a refreshed BPM estimate once
+    /// the envelope window fills.
+    fn process(&mut self, mags: &[f32], hop_samples: usize) -> (bool, Option<f32>) {
+        self.hop_rate = self.sample_rate / hop_samples as f32;
+        self.window_len = ((1.5 * self.hop_rate).round() as usize).max(8);
+        self.refractory_hops = ((0.1 * self.hop_rate).round() as usize).max(1);
+
+        let flux: f32 = mags
+            .iter()
+            .zip(&self.prev_mag)
+            .map(|(mag, prev)| (mag - prev).max(0.0))
+            .sum();
+        self.prev_mag.copy_from_slice(mags);
+
+        self.envelope.push_back(flux);
+        while self.envelope.len() > self.window_len {
+            self.envelope.pop_front();
+        }
+        self.hops_since_onset += 1;
+
+        let mean = self.envelope.iter().sum::<f32>() / self.envelope.len() as f32;
+        let variance = self.envelope.iter().map(|v| (v - mean).powi(2)).sum::<f32>()
+            / self.envelope.len() as f32;
+        let threshold = mean + self.sensitivity * variance.sqrt();
+        self.last_mean = mean;
+
+        let is_onset = flux > threshold && self.hops_since_onset >= self.refractory_hops;
+        if is_onset {
+            self.hops_since_onset = 0;
+        }
+
+        let bpm = if self.envelope.len() == self.window_len {
+            self.estimate_bpm()
+        } else {
+            None
+        };
+        (is_onset, bpm)
+    }
+
+    /// Autocorrelates the mean-centered onset envelope over lags spanning the
+    /// configured BPM range and converts the strongest lag back into a tempo
+    /// estimate. Centering removes the envelope's DC/energy floor and dividing
+    /// each lag's score by its overlap count keeps shorter lags from being
+    /// favored just because they sum more terms; without both, the score is
+    /// dominated by envelope energy and biased towards `min_lag` (≈ `bpm_max`).
+    fn estimate_bpm(&self) -> Option<f32> {
+        let min_lag = ((60.0 * self.hop_rate / self.bpm_max).floor() as usize).max(1);
+        let max_lag =
+            ((60.0 * self.hop_rate / self.bpm_min).ceil() as usize).min(self.envelope.len() - 1);
+        if min_lag >= max_lag {
+            return None;
+        }
+
+        let centered: Vec<f32> = self.envelope.iter().map(|v| v - self.last_mean).collect();
+        let (best_lag, _) = (min_lag..=max_lag)
+            .map(|lag| {
+                let overlap = (centered.len() - lag) as f32;
+                let score: f32 = centered
+                    .iter()
+                    .zip(&centered[lag..])
+                    .map(|(a, b)| a * b)
+                    .sum::<f32>()
+                    / overlap;
+                (lag, score)
+            })
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())?;
+
+        Some(60.0 * self.hop_rate / best_lag as f32)
+    }
+}
+
+fn run(app: AppHandle, audio_cfg: AudioConfig) -> anyhow::Result<()> {
     let host = cpal::default_host();
     let device = host.default_input_device().ok_or_else(|| anyhow::anyhow!("no input device"))?;
     let config = device.default_input_config()?;
+    let sample_rate = config.sample_rate().0 as f32;
     let fft_size = 1024usize;
     let mut planner = FftPlanner::<f32>::new();
     let fft = planner.plan_fft_forward(fft_size);
     let mut buffer: Vec<Complex<f32>> = vec![Complex{ re:0.0, im:0.0}; fft_size];
+    let mut detector = BeatDetector::new(fft_size, sample_rate, audio_cfg);
 
     let stream = device.build_input_stream(
         &config.into(),
@@ -28,7 +144,14 @@ fn run(app: AppHandle) -> anyhow::Result<()> {
             }
             fft.process(&mut buffer);
             let mags: Vec<f32> = buffer.iter().map(|c| c.norm()).collect();
+            let (onset, bpm) = detector.process(&mags, data.len());
             let _ = app.emit_all("fft", mags);
+            if onset {
+                let _ = app.emit_all("beat", ());
+            }
+            if let Some(bpm) = bpm {
+                let _ = app.emit_all("bpm", bpm);
+            }
         },
         move |err| eprintln!("stream error: {err}")
     )?;
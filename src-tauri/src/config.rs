@@ -15,9 +15,25 @@ impl Default for LayerConfig {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AudioConfig {
+    pub sensitivity: f32,
+    pub bpm_min: f32,
+    pub bpm_max: f32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self { sensitivity: 1.5, bpm_min: 60.0, bpm_max: 200.0 }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Config {
+    #[serde(default)]
     pub layers: HashMap<String, LayerConfig>,
+    #[serde(default)]
+    pub audio: AudioConfig,
 }
 
 impl Default for Config {
@@ -26,7 +42,7 @@ impl Default for Config {
         layers.insert("A".into(), LayerConfig { midi_channel: 14, ..Default::default() });
         layers.insert("B".into(), LayerConfig { midi_channel: 15, ..Default::default() });
         layers.insert("C".into(), LayerConfig { midi_channel: 16, ..Default::default() });
-        Self { layers }
+        Self { layers, audio: AudioConfig::default() }
     }
 }
 
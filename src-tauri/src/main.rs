@@ -46,7 +46,8 @@ fn main() {
         ])
         .setup(|app| {
             midi::start(app.handle().clone());
-            audio::start(app.handle().clone());
+            let audio_cfg = app.state::<ConfigState>().inner.lock().unwrap().audio.clone();
+            audio::start(app.handle().clone(), audio_cfg);
             tauri::async_runtime::spawn(gpu::init());
             Ok(())
         })